@@ -1,8 +1,16 @@
 use pathdiff::diff_paths;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap as StdHashMap;
 use std::default::Default;
 use std::fs;
-use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::UNIX_EPOCH;
 use walkdir::WalkDir;
 
 pub use im::HashMap;
@@ -11,61 +19,195 @@ pub use yaml_rust::Yaml;
 
 mod frontmatter;
 
+/// Name of the dirstate file persisted into the destination directory so that
+/// incremental state survives across process restarts.
+const DIRSTATE_FILE: &str = ".shtola.dirstate.json";
+
 pub struct Shtola {
 	ware: Ware<IR>,
+	fs: Box<dyn Fs>,
 	ir: IR,
 }
 
 impl Shtola {
 	pub fn new() -> Shtola {
-		let config: Config = Default::default();
+		let config = Config {
+			frontmatter: true,
+			frontmatter_extensions: vec!["md".into(), "markdown".into(), "html".into(), "txt".into()],
+			..Default::default()
+		};
 		let ir = IR {
 			files: HashMap::new(),
 			config,
 		};
 		Shtola {
 			ware: Ware::new(),
+			fs: Box::new(RealFs),
 			ir,
 		}
 	}
 
+	pub fn fs(&mut self, fs: Box<dyn Fs>) {
+		self.fs = fs;
+	}
+
 	pub fn ignores(&mut self, vec: &mut Vec<PathBuf>) {
 		self.ir.config.ignores.append(vec);
 		self.ir.config.ignores.dedup();
 	}
 
-	pub fn source<T: Into<PathBuf>>(&mut self, path: T) {
-		self.ir.config.source = fs::canonicalize(path.into()).unwrap();
+	pub fn source<T: Into<PathBuf>>(&mut self, path: T) -> Result<(), ShtolaError> {
+		let path = path.into();
+		self.ir.config.source = self
+			.fs
+			.canonicalize(&path)
+			.map_err(|source| ShtolaError::Io { path, source })?;
+		Ok(())
 	}
 
-	pub fn destination<T: Into<PathBuf> + Clone>(&mut self, path: T) {
-		fs::create_dir_all(path.clone().into()).expect("Unable to create destination directory!");
-		self.ir.config.destination = fs::canonicalize(path.into()).unwrap();
+	pub fn destination<T: Into<PathBuf> + Clone>(&mut self, path: T) -> Result<(), ShtolaError> {
+		let path_buf = path.clone().into();
+		self.fs
+			.create_dir_all(&path_buf)
+			.map_err(|source| ShtolaError::Io { path: path_buf.clone(), source })?;
+		self.ir.config.destination = self
+			.fs
+			.canonicalize(&path.into())
+			.map_err(|source| ShtolaError::Io { path: path_buf, source })?;
+		Ok(())
 	}
 
 	pub fn clean(&mut self, b: bool) {
 		self.ir.config.clean = b;
 	}
 
+	/// Enables incremental rebuilds: a dirstate persisted into the destination
+	/// is consulted so unchanged source files are skipped on the next build.
+	/// Off by default, so an ordinary build stays idempotent and never writes
+	/// [`DIRSTATE_FILE`] into the destination.
+	pub fn incremental(&mut self, b: bool) {
+		self.ir.config.incremental = b;
+	}
+
+	/// Toggles frontmatter lexing. On by default; pass `false` to carry every
+	/// file through untouched regardless of [`frontmatter_extensions`].
+	///
+	/// [`frontmatter_extensions`]: Shtola::frontmatter_extensions
 	pub fn frontmatter(&mut self, b: bool) {
 		self.ir.config.frontmatter = b;
 	}
 
+	/// Sets the extension allowlist deciding which decodable text files are run
+	/// through the frontmatter lexer. Files outside the list (and every binary
+	/// file) are carried through untouched.
+	pub fn frontmatter_extensions(&mut self, exts: Vec<String>) {
+		self.ir.config.frontmatter_extensions = exts;
+	}
+
+	/// Sets the line-ending policy applied on read and write.
+	pub fn line_ending(&mut self, le: LineEnding) {
+		self.ir.config.line_ending = le;
+	}
+
+	/// Caps how many files the read and write phases process at once. Pass `1`
+	/// for a deterministic single-threaded build; leave unset to fan the work
+	/// out across the default rayon thread pool.
+	pub fn concurrency(&mut self, n: usize) {
+		self.ir.config.concurrency = Some(n);
+	}
+
 	pub fn register(&mut self, func: Box<dyn Fn(IR) -> IR>) {
 		self.ware.wrap(func);
 	}
 
-	pub fn build(&mut self) -> Result<IR, std::io::Error> {
+	pub fn build(&mut self) -> Result<IR, ShtolaError> {
+		let dest = self.ir.config.destination.clone();
+		let incremental = self.ir.config.incremental;
+		let state_path = dest.join(DIRSTATE_FILE);
+		let fingerprint = config_fingerprint(&self.ir.config);
+		// Load the previous dirstate, but throw it away if the config changed
+		// (ignores/frontmatter flags), the caller asked for a clean build, or
+		// incremental rebuilds aren't enabled — each means every file should
+		// be treated as new.
+		let mut prev = if incremental {
+			self.fs
+				.read_file_bytes(&state_path)
+				.and_then(|b| serde_json::from_slice::<Dirstate>(&b).ok())
+				.unwrap_or_default()
+		} else {
+			Dirstate::default()
+		};
+		if prev.config_hash != fingerprint || self.ir.config.clean {
+			prev = Dirstate::default();
+		}
+
 		if self.ir.config.clean {
-			fs::remove_dir_all(&self.ir.config.destination)?;
-			fs::create_dir_all(&self.ir.config.destination).expect("Unable to recreate destination directory!");
+			self.fs
+				.remove_dir_all(&dest)
+				.map_err(|source| ShtolaError::Io { path: dest.clone(), source })?;
+			self.fs
+				.create_dir_all(&dest)
+				.expect("Unable to recreate destination directory!");
 		}
-		let files = read_dir(&self.ir.config.source)?;
+
+		let (files, mut dirstate) =
+			self.fs.read_dir(&self.ir.config.source, &self.ir.config, &prev)?;
 		self.ir.files = files;
 		let result_ir = self.ware.run(self.ir.clone());
-		write_dir(result_ir.clone(), &self.ir.config.destination)?;
+		self.fs
+			.write_dir(result_ir.clone(), &dest, &prev, &mut dirstate)?;
+
+		if incremental {
+			dirstate.config_hash = fingerprint;
+			let bytes = serde_json::to_vec(&dirstate).map_err(|e| ShtolaError::Io {
+				path: state_path.clone(),
+				source: io::Error::new(io::ErrorKind::Other, e),
+			})?;
+			self.fs
+				.write_file_bytes(&state_path, &bytes)
+				.map_err(|source| ShtolaError::Io { path: state_path, source })?;
+		}
 		Ok(result_ir)
 	}
+
+	/// Performs an initial full build, then blocks listening for filesystem
+	/// events on the source directory, running a targeted incremental rebuild
+	/// through the registered [`Ware`] pipeline whenever something changes.
+	///
+	/// Every rebuild this drives is incremental, regardless of what the
+	/// caller set via [`Shtola::incremental`] — a non-incremental rebuild on
+	/// every filesystem event would defeat the point of watching.
+	pub fn watch(&mut self) -> Result<(), ShtolaError> {
+		use notify::{RecursiveMode, Watcher};
+		use std::sync::mpsc::channel;
+
+		self.ir.config.incremental = true;
+
+		self.build()?;
+
+		let source = self.ir.config.source.clone();
+		let (tx, rx) = channel();
+		let mut watcher = notify::recommended_watcher(move |res| {
+			let _ = tx.send(res);
+		})
+		.map_err(|e| ShtolaError::Io {
+			path: source.clone(),
+			source: io::Error::new(io::ErrorKind::Other, e),
+		})?;
+		watcher
+			.watch(&self.ir.config.source, RecursiveMode::Recursive)
+			.map_err(|e| ShtolaError::Io {
+				path: self.ir.config.source.clone(),
+				source: io::Error::new(io::ErrorKind::Other, e),
+			})?;
+
+		for res in rx {
+			if res.is_ok() {
+				self.build()?;
+			}
+		}
+		Ok(())
+	}
 }
 
 #[derive(Debug, Clone)]
@@ -80,51 +222,505 @@ pub struct Config {
 	source: PathBuf,
 	destination: PathBuf,
 	clean: bool,
+	incremental: bool,
 	frontmatter: bool,
+	frontmatter_extensions: Vec<String>,
+	line_ending: LineEnding,
+	concurrency: Option<usize>,
+}
+
+/// Line-ending policy applied when reading and writing text files.
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub enum LineEnding {
+	/// Preserve each file's original dominant style on write.
+	Auto,
+	/// Always write `\n`.
+	Lf,
+	/// Always write `\r\n`.
+	Crlf,
+}
+
+impl Default for LineEnding {
+	fn default() -> LineEnding {
+		LineEnding::Auto
+	}
+}
+
+/// Whether a file was decoded as UTF-8 text (and so can carry frontmatter) or
+/// carried through verbatim as raw bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileKind {
+	Text,
+	Binary,
 }
 
 #[derive(Debug, Clone)]
 pub struct ShFile {
 	frontmatter: Vec<Yaml>,
 	content: Vec<u8>,
+	kind: FileKind,
+	/// Dominant line-ending style detected when the file was read. Used to
+	/// restore the original style under [`LineEnding::Auto`].
+	line_ending: LineEnding,
 }
 
-fn read_dir(source: &PathBuf) -> Result<HashMap<PathBuf, ShFile>, std::io::Error> {
-	let mut result = HashMap::new();
-	let iters = WalkDir::new(source)
-		.into_iter()
-		.filter_map(|e| e.ok())
-		.filter(|e| !e.path().is_dir());
-	for entry in iters {
-		let path = entry.path();
-		let mut content = String::new();
-		fs::File::open(path)?.read_to_string(&mut content)?;
-		let (matter, content) = frontmatter::lexer(&content);
-		let yaml = frontmatter::to_yaml(&matter);
-		let file = ShFile {
-			frontmatter: yaml,
-			content: content.into(),
-		};
-		let rel_path = diff_paths(path, source).unwrap();
-		result.insert(rel_path, file);
+/// Per-file bookkeeping recorded at the end of a build so the next one can
+/// tell what actually changed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileMeta {
+	/// Source modification time in milliseconds since the epoch. Millisecond
+	/// resolution (rather than whole seconds) keeps same-second edits that
+	/// leave the file size unchanged from being mistaken for no-ops.
+	mtime: u64,
+	/// Source size in bytes.
+	size: u64,
+	/// Hash of the content last written to the destination.
+	hash: u64,
+}
+
+/// A snapshot of the last build, keyed by destination-relative path. Persisted
+/// into the destination directory as [`DIRSTATE_FILE`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Dirstate {
+	config_hash: u64,
+	entries: StdHashMap<PathBuf, FileMeta>,
+}
+
+/// Everything that can go wrong during a build, each variant carrying the
+/// offending path and (where there is one) a `source` error so callers get a
+/// full context chain.
+#[derive(Debug)]
+pub enum ShtolaError {
+	Io {
+		path: PathBuf,
+		source: io::Error,
+	},
+	FrontmatterParse {
+		path: PathBuf,
+		source: yaml_rust::ScanError,
+	},
+	PathResolution {
+		path: PathBuf,
+	},
+}
+
+impl std::fmt::Display for ShtolaError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			ShtolaError::Io { path, .. } => {
+				write!(f, "IO error while handling {}", path.display())
+			}
+			ShtolaError::FrontmatterParse { path, .. } => {
+				write!(f, "failed to parse frontmatter in {}", path.display())
+			}
+			ShtolaError::PathResolution { path } => {
+				write!(f, "could not resolve path {}", path.display())
+			}
+		}
+	}
+}
+
+impl std::error::Error for ShtolaError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			ShtolaError::Io { source, .. } => Some(source),
+			ShtolaError::FrontmatterParse { source, .. } => Some(source),
+			ShtolaError::PathResolution { .. } => None,
+		}
+	}
+}
+
+/// A filesystem backend used by [`Shtola::build`] for all reads and writes.
+///
+/// The default backend is [`RealFs`], which talks to `std::fs`. [`FakeFs`]
+/// keeps everything in an `im::HashMap` so middleware pipelines can be
+/// exercised in unit tests without touching disk.
+pub trait Fs {
+	fn read_dir(
+		&self,
+		source: &Path,
+		config: &Config,
+		prev: &Dirstate,
+	) -> Result<(HashMap<PathBuf, ShFile>, Dirstate), ShtolaError>;
+	fn write_dir(
+		&self,
+		ir: IR,
+		dest: &Path,
+		prev: &Dirstate,
+		dirstate: &mut Dirstate,
+	) -> Result<(), ShtolaError>;
+	fn create_dir_all(&self, path: &Path) -> Result<(), std::io::Error>;
+	fn remove_dir_all(&self, path: &Path) -> Result<(), std::io::Error>;
+	fn canonicalize(&self, path: &Path) -> Result<PathBuf, std::io::Error>;
+	fn read_file_bytes(&self, path: &Path) -> Option<Vec<u8>>;
+	fn write_file_bytes(&self, path: &Path, bytes: &[u8]) -> Result<(), std::io::Error>;
+}
+
+/// Filesystem backend backed by `std::fs`.
+pub struct RealFs;
+
+impl Fs for RealFs {
+	fn read_dir(
+		&self,
+		source: &Path,
+		config: &Config,
+		prev: &Dirstate,
+	) -> Result<(HashMap<PathBuf, ShFile>, Dirstate), ShtolaError> {
+		let mut result = HashMap::new();
+		let mut dirstate = Dirstate::default();
+		// Drain the walk into a Vec first so the per-file read and
+		// frontmatter parse can fan out across the worker pool.
+		let entries: Vec<_> = WalkDir::new(source)
+			.into_iter()
+			.filter_map(|e| e.ok())
+			.filter(|e| !e.path().is_dir())
+			.collect();
+
+		let outcomes = map_collect(entries, config.concurrency, |entry| {
+			let path = entry.path();
+			let rel_path = diff_paths(path, source)
+				.ok_or_else(|| ShtolaError::PathResolution { path: path.to_path_buf() })?;
+			let meta = entry.metadata().map_err(|e| ShtolaError::Io {
+				path: path.to_path_buf(),
+				source: io::Error::new(io::ErrorKind::Other, e),
+			})?;
+			let size = meta.len();
+			let mtime = meta
+				.modified()
+				.ok()
+				.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+				.map(|d| d.as_millis() as u64)
+				.unwrap_or(0);
+
+			// Unchanged source: skip the re-read, carry the old metadata
+			// forward so it still appears in the persisted dirstate.
+			if let Some(old) = prev.entries.get(&rel_path) {
+				if old.mtime == mtime && old.size == size {
+					return Ok((rel_path, None, old.clone()));
+				}
+			}
+
+			let mut bytes = Vec::new();
+			fs::File::open(path)
+				.and_then(|mut f| f.read_to_end(&mut bytes))
+				.map_err(|source| ShtolaError::Io { path: path.to_path_buf(), source })?;
+			let file = parse_file(&rel_path, bytes, config)
+				.map_err(|source| ShtolaError::FrontmatterParse { path: path.to_path_buf(), source })?;
+			Ok((rel_path, Some(file), FileMeta { mtime, size, hash: 0 }))
+		})?;
+
+		// Merge the per-file results back into the shared maps sequentially.
+		for (rel_path, file, meta) in outcomes {
+			dirstate.entries.insert(rel_path.clone(), meta);
+			if let Some(file) = file {
+				result.insert(rel_path, file);
+			}
+		}
+		Ok((result, dirstate))
+	}
+
+	fn write_dir(
+		&self,
+		ir: IR,
+		dest: &Path,
+		prev: &Dirstate,
+		dirstate: &mut Dirstate,
+	) -> Result<(), ShtolaError> {
+		let policy = ir.config.line_ending.clone();
+		let concurrency = ir.config.concurrency;
+		// Update the dirstate hashes and decide what to rewrite up front
+		// (the dirstate is `&mut` and can't be shared across threads), then
+		// fan the actual writes out across the worker pool.
+		let mut pending = Vec::new();
+		for (path, file) in ir.files {
+			let hash = hash_bytes(&file.content);
+			if let Some(meta) = dirstate.entries.get_mut(&path) {
+				meta.hash = hash;
+			}
+			// Only rewrite the destination if the output content actually
+			// differs from what we wrote last time.
+			if prev.entries.get(&path).map(|m| m.hash) == Some(hash) {
+				continue;
+			}
+			pending.push((path, file));
+		}
+
+		map_collect(pending, concurrency, |(path, file)| {
+			let bytes = render_file(&file, &policy);
+			let dest_path = dest.join(path);
+			fs::create_dir_all(dest_path.parent().unwrap())
+				.and_then(|_| fs::File::create(&dest_path))
+				.and_then(|mut f| f.write_all(&bytes))
+				.map_err(|source| ShtolaError::Io { path: dest_path.clone(), source })
+		})?;
+		Ok(())
+	}
+
+	fn create_dir_all(&self, path: &Path) -> Result<(), std::io::Error> {
+		fs::create_dir_all(path)
+	}
+
+	fn remove_dir_all(&self, path: &Path) -> Result<(), std::io::Error> {
+		fs::remove_dir_all(path)
+	}
+
+	fn canonicalize(&self, path: &Path) -> Result<PathBuf, std::io::Error> {
+		fs::canonicalize(path)
+	}
+
+	fn read_file_bytes(&self, path: &Path) -> Option<Vec<u8>> {
+		fs::read(path).ok()
+	}
+
+	fn write_file_bytes(&self, path: &Path, bytes: &[u8]) -> Result<(), std::io::Error> {
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent)?;
+		}
+		fs::write(path, bytes)
+	}
+}
+
+/// In-memory filesystem backend backed by a persistent `im::HashMap` of raw
+/// bytes keyed by absolute path.
+///
+/// Clones share the same underlying store, so a test can hold on to a handle,
+/// run a build, and then inspect everything `write_dir` produced.
+#[derive(Clone, Default)]
+pub struct FakeFs {
+	files: Rc<RefCell<im::HashMap<PathBuf, Vec<u8>>>>,
+}
+
+impl FakeFs {
+	pub fn new() -> FakeFs {
+		FakeFs {
+			files: Rc::new(RefCell::new(im::HashMap::new())),
+		}
+	}
+
+	/// Seeds a source file at `path` with the given raw bytes.
+	pub fn add_file<T: Into<PathBuf>>(&self, path: T, content: &[u8]) {
+		self.files.borrow_mut().insert(path.into(), content.to_vec());
+	}
+
+	/// Returns the raw bytes currently stored at `path`, if any.
+	pub fn get_file<T: Into<PathBuf>>(&self, path: T) -> Option<Vec<u8>> {
+		self.files.borrow().get(&path.into()).cloned()
+	}
+}
+
+impl Fs for FakeFs {
+	fn read_dir(
+		&self,
+		source: &Path,
+		config: &Config,
+		prev: &Dirstate,
+	) -> Result<(HashMap<PathBuf, ShFile>, Dirstate), ShtolaError> {
+		let mut result = HashMap::new();
+		let mut dirstate = Dirstate::default();
+		for (path, bytes) in self.files.borrow().iter() {
+			if !path.starts_with(source) {
+				continue;
+			}
+			let rel_path = diff_paths(path, source)
+				.ok_or_else(|| ShtolaError::PathResolution { path: path.clone() })?;
+			let size = bytes.len() as u64;
+			// There is no real mtime in memory, so hash the bytes and use that
+			// as a stand-in: any content change registers as a modification.
+			let mtime = hash_bytes(bytes);
+
+			if let Some(old) = prev.entries.get(&rel_path) {
+				if old.mtime == mtime && old.size == size {
+					dirstate.entries.insert(rel_path, old.clone());
+					continue;
+				}
+			}
+
+			let file = parse_file(&rel_path, bytes.clone(), config)
+				.map_err(|source| ShtolaError::FrontmatterParse { path: path.clone(), source })?;
+			dirstate.entries.insert(
+				rel_path.clone(),
+				FileMeta {
+					mtime,
+					size,
+					hash: 0,
+				},
+			);
+			result.insert(rel_path, file);
+		}
+		Ok((result, dirstate))
+	}
+
+	fn write_dir(
+		&self,
+		ir: IR,
+		dest: &Path,
+		prev: &Dirstate,
+		dirstate: &mut Dirstate,
+	) -> Result<(), ShtolaError> {
+		let policy = ir.config.line_ending.clone();
+		let mut store = self.files.borrow_mut();
+		for (path, file) in ir.files {
+			let hash = hash_bytes(&file.content);
+			if let Some(meta) = dirstate.entries.get_mut(&path) {
+				meta.hash = hash;
+			}
+			if prev.entries.get(&path).map(|m| m.hash) == Some(hash) {
+				continue;
+			}
+			store.insert(dest.join(path), render_file(&file, &policy));
+		}
+		Ok(())
+	}
+
+	fn create_dir_all(&self, _path: &Path) -> Result<(), std::io::Error> {
+		Ok(())
+	}
+
+	fn remove_dir_all(&self, path: &Path) -> Result<(), std::io::Error> {
+		self.files.borrow_mut().retain(|p, _| !p.starts_with(path));
+		Ok(())
+	}
+
+	fn canonicalize(&self, path: &Path) -> Result<PathBuf, std::io::Error> {
+		Ok(path.to_path_buf())
+	}
+
+	fn read_file_bytes(&self, path: &Path) -> Option<Vec<u8>> {
+		self.files.borrow().get(&path.to_path_buf()).cloned()
+	}
+
+	fn write_file_bytes(&self, path: &Path, bytes: &[u8]) -> Result<(), std::io::Error> {
+		self.files.borrow_mut().insert(path.to_path_buf(), bytes.to_vec());
+		Ok(())
+	}
+}
+
+/// Turns raw file bytes into an [`ShFile`]. Files that decode as UTF-8 and
+/// whose extension is in the allowlist are run through the frontmatter lexer;
+/// everything else (other text files and binary assets) is carried through
+/// verbatim so nothing gets corrupted on the way to the destination.
+fn parse_file(
+	rel_path: &Path,
+	bytes: Vec<u8>,
+	config: &Config,
+) -> Result<ShFile, yaml_rust::ScanError> {
+	match String::from_utf8(bytes) {
+		Ok(text) => {
+			let line_ending = detect_line_ending(&text);
+			let (frontmatter, body): (Vec<Yaml>, String) =
+				if wants_frontmatter(rel_path, config.frontmatter, &config.frontmatter_extensions) {
+					let (matter, content) = frontmatter::lexer(&text);
+					(frontmatter::to_yaml(&matter)?, content.into())
+				} else {
+					(Vec::new(), text)
+				};
+			// Normalize to LF internally so middleware always sees one style.
+			Ok(ShFile {
+				frontmatter,
+				content: body.replace("\r\n", "\n").into_bytes(),
+				kind: FileKind::Text,
+				line_ending,
+			})
+		}
+		Err(e) => Ok(ShFile {
+			frontmatter: Vec::new(),
+			content: e.into_bytes(),
+			kind: FileKind::Binary,
+			line_ending: LineEnding::Auto,
+		}),
+	}
+}
+
+/// Detects the dominant newline style in a text file, defaulting to LF when
+/// there are no line endings at all.
+fn detect_line_ending(text: &str) -> LineEnding {
+	let crlf = text.matches("\r\n").count();
+	let lf = text.matches('\n').count() - crlf;
+	if crlf > lf {
+		LineEnding::Crlf
+	} else {
+		LineEnding::Lf
 	}
-	Ok(result)
 }
 
-fn write_dir(ir: IR, dest: &PathBuf) -> Result<(), std::io::Error> {
-	for (path, file) in ir.files {
-		let dest_path = dest.join(path);
-		fs::create_dir_all(dest_path.parent().unwrap()).expect("Unable to create destination subdirectory!");
-		fs::File::create(dest_path)?.write_all(&file.content)?;
+/// Renders an [`ShFile`] to the bytes that should land on disk, re-applying the
+/// configured line-ending policy to text files. Binary files are returned as-is.
+fn render_file(file: &ShFile, policy: &LineEnding) -> Vec<u8> {
+	if file.kind != FileKind::Text {
+		return file.content.clone();
+	}
+	let target = match policy {
+		LineEnding::Auto => &file.line_ending,
+		other => other,
+	};
+	match target {
+		LineEnding::Crlf => {
+			let text = String::from_utf8_lossy(&file.content);
+			text.replace('\n', "\r\n").into_bytes()
+		}
+		_ => file.content.clone(),
 	}
-	Ok(())
+}
+
+fn wants_frontmatter(path: &Path, enabled: bool, exts: &[String]) -> bool {
+	enabled
+		&& path
+			.extension()
+			.and_then(|e| e.to_str())
+			.map(|ext| exts.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+			.unwrap_or(false)
+}
+
+/// Maps `f` over `items`, collecting into a `Result` and short-circuiting on
+/// the first error. The work runs on the rayon pool unless `concurrency` is
+/// `Some(1)`, which keeps it single-threaded for deterministic ordering;
+/// `Some(n)` caps the pool at `n` workers and `None` uses the global default.
+fn map_collect<T, R, F>(
+	items: Vec<T>,
+	concurrency: Option<usize>,
+	f: F,
+) -> Result<Vec<R>, ShtolaError>
+where
+	T: Send,
+	R: Send,
+	F: Fn(T) -> Result<R, ShtolaError> + Sync + Send,
+{
+	match concurrency {
+		Some(1) => items.into_iter().map(f).collect(),
+		Some(n) => rayon::ThreadPoolBuilder::new()
+			.num_threads(n)
+			.build()
+			.map_err(|e| ShtolaError::Io {
+				path: PathBuf::new(),
+				source: io::Error::new(io::ErrorKind::Other, e),
+			})?
+			.install(|| items.into_par_iter().map(f).collect()),
+		None => items.into_par_iter().map(f).collect(),
+	}
+}
+
+fn hash_bytes(bytes: &[u8]) -> u64 {
+	let mut hasher = DefaultHasher::new();
+	bytes.hash(&mut hasher);
+	hasher.finish()
+}
+
+/// Fingerprint of the build-affecting configuration. A change here invalidates
+/// the whole dirstate on the next build.
+fn config_fingerprint(config: &Config) -> u64 {
+	let mut hasher = DefaultHasher::new();
+	config.ignores.hash(&mut hasher);
+	config.frontmatter.hash(&mut hasher);
+	config.frontmatter_extensions.hash(&mut hasher);
+	config.line_ending.hash(&mut hasher);
+	hasher.finish()
 }
 
 #[test]
 fn read_works() {
 	let mut s = Shtola::new();
-	s.source("../fixtures/simple");
-	s.destination("./");
+	s.source("../fixtures/simple").unwrap();
+	s.destination("./").unwrap();
 	let r = s.build().unwrap();
 	assert_eq!(r.files.len(), 1);
 	let keys: Vec<&PathBuf> = r.files.keys().collect();
@@ -134,8 +730,8 @@ fn read_works() {
 #[test]
 fn clean_works() {
 	let mut s = Shtola::new();
-	s.source("../fixtures/simple");
-	s.destination("../fixtures/dest_clean");
+	s.source("../fixtures/simple").unwrap();
+	s.destination("../fixtures/dest_clean").unwrap();
 	s.clean(true);
 	fs::create_dir_all("../fixtures/dest_clean").unwrap();
 	fs::write("../fixtures/dest_clean/blah.foo", "").unwrap();
@@ -147,8 +743,8 @@ fn clean_works() {
 #[test]
 fn write_works() {
 	let mut s = Shtola::new();
-	s.source("../fixtures/simple");
-	s.destination("../fixtures/dest");
+	s.source("../fixtures/simple").unwrap();
+	s.destination("../fixtures/dest").unwrap();
 	s.clean(true);
 	let mw = Box::new(|ir: IR| {
 		let mut update_hash: HashMap<PathBuf, ShFile> = HashMap::new();
@@ -156,6 +752,8 @@ fn write_works() {
 			update_hash.insert(k.into(), ShFile {
 				frontmatter: v.frontmatter.clone(),
 				content: "hello".into(),
+				kind: v.kind.clone(),
+				line_ending: v.line_ending.clone(),
 			});
 		}
 		IR { files: update_hash.union(ir.files), ..ir }
@@ -167,4 +765,125 @@ fn write_works() {
 	let file = &fs::read(dpath).unwrap();
 	let fstring = String::from_utf8_lossy(file);
 	assert_eq!(fstring, "hello");
-}
\ No newline at end of file
+}
+
+#[test]
+fn fake_fs_build_works() {
+	let fs = FakeFs::new();
+	fs.add_file("/src/hello.txt", b"hello");
+	let mut s = Shtola::new();
+	s.fs(Box::new(fs.clone()));
+	s.source("/src").unwrap();
+	s.destination("/dest").unwrap();
+	let mw = Box::new(|ir: IR| {
+		let mut update_hash: HashMap<PathBuf, ShFile> = HashMap::new();
+		for (k, v) in &ir.files {
+			update_hash.insert(k.into(), ShFile {
+				frontmatter: v.frontmatter.clone(),
+				content: "goodbye".into(),
+				kind: v.kind.clone(),
+				line_ending: v.line_ending.clone(),
+			});
+		}
+		IR { files: update_hash.union(ir.files), ..ir }
+	});
+	s.register(mw);
+	let r = s.build().unwrap();
+	assert_eq!(r.files.len(), 1);
+	assert_eq!(fs.get_file("/dest/hello.txt").unwrap(), b"goodbye");
+}
+
+#[test]
+fn binary_files_pass_through_untouched() {
+	let bytes: Vec<u8> = vec![0x00, 0x9f, 0x92, 0xa9, 0xff];
+	let fs = FakeFs::new();
+	fs.add_file("/src/logo.png", &bytes);
+	let mut s = Shtola::new();
+	s.fs(Box::new(fs.clone()));
+	s.source("/src").unwrap();
+	s.destination("/dest").unwrap();
+	s.build().unwrap();
+	assert_eq!(fs.get_file("/dest/logo.png").unwrap(), bytes);
+}
+
+#[test]
+fn frontmatter_disabled_skips_lexing() {
+	let fs = FakeFs::new();
+	fs.add_file("/src/hello.md", b"---\ntitle: hi\n---\nbody");
+	let mut s = Shtola::new();
+	s.fs(Box::new(fs.clone()));
+	s.source("/src").unwrap();
+	s.destination("/dest").unwrap();
+	s.frontmatter(false);
+	let r = s.build().unwrap();
+	let file = r.files.get(&PathBuf::from("hello.md")).unwrap();
+	assert!(file.frontmatter.is_empty());
+	assert_eq!(file.content, b"---\ntitle: hi\n---\nbody");
+}
+
+#[test]
+fn incremental_skips_unchanged() {
+	let fs = FakeFs::new();
+	fs.add_file("/src/hello.txt", b"hello");
+	let mut s = Shtola::new();
+	s.fs(Box::new(fs.clone()));
+	s.source("/src").unwrap();
+	s.destination("/dest").unwrap();
+	s.incremental(true);
+	let first = s.build().unwrap();
+	assert_eq!(first.files.len(), 1);
+	// Nothing changed on disk, so the second build re-reads nothing.
+	let second = s.build().unwrap();
+	assert_eq!(second.files.len(), 0);
+}
+
+#[test]
+fn non_incremental_builds_stay_idempotent() {
+	let fs = FakeFs::new();
+	fs.add_file("/src/hello.txt", b"hello");
+	let mut s = Shtola::new();
+	s.fs(Box::new(fs.clone()));
+	s.source("/src").unwrap();
+	s.destination("/dest").unwrap();
+	let first = s.build().unwrap();
+	assert_eq!(first.files.len(), 1);
+	// Incremental rebuilds are opt-in, so a second build re-reads everything
+	// and never persists DIRSTATE_FILE into the destination.
+	let second = s.build().unwrap();
+	assert_eq!(second.files.len(), 1);
+	assert!(fs.get_file(PathBuf::from("/dest").join(DIRSTATE_FILE)).is_none());
+}
+
+#[test]
+fn crlf_is_normalized_and_reapplied() {
+	let fs = FakeFs::new();
+	fs.add_file("/src/win.txt", b"one\r\ntwo\r\nthree");
+	let mut s = Shtola::new();
+	s.fs(Box::new(fs.clone()));
+	s.source("/src").unwrap();
+	s.destination("/dest").unwrap();
+	// Middleware sees LF-only content regardless of the source style.
+	let mw = Box::new(|ir: IR| {
+		for v in ir.files.values() {
+			assert_eq!(v.content.iter().filter(|&&b| b == b'\r').count(), 0);
+		}
+		ir
+	});
+	s.register(mw);
+	s.build().unwrap();
+	// Under the default `Auto` policy the original CRLF style is restored.
+	assert_eq!(fs.get_file("/dest/win.txt").unwrap(), b"one\r\ntwo\r\nthree");
+}
+
+#[test]
+fn line_ending_lf_rewrites_crlf_sources() {
+	let fs = FakeFs::new();
+	fs.add_file("/src/win.txt", b"one\r\ntwo");
+	let mut s = Shtola::new();
+	s.fs(Box::new(fs.clone()));
+	s.source("/src").unwrap();
+	s.destination("/dest").unwrap();
+	s.line_ending(LineEnding::Lf);
+	s.build().unwrap();
+	assert_eq!(fs.get_file("/dest/win.txt").unwrap(), b"one\ntwo");
+}